@@ -1,5 +1,8 @@
 use std::str::FromStr;
 
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+
 // Decode hex string into Vec<u8>, return error string on failure
 pub fn decode_hex(hex_str: &str) -> Result<Vec<u8>, String> {
     hex::decode(hex_str).map_err(|e| format!("Failed to decode hex: {}", e))
@@ -32,23 +35,149 @@ pub fn parse_satoshis(input: &str) -> Result<u64, String> {
     u64::from_str(input).map_err(|_e| "Invalid satoshi amount".to_string())
 }
 
+// Unit variants, so the default serde derive already renders this as a tagged
+// string ("P2PKH", "P2WPKH", ...) rather than needing a manual impl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ScriptType {
     P2PKH,
     P2WPKH,
+    P2SH,
+    P2TR,
     Unknown,
 }
 
 // Match script pattern and return corresponding ScriptType
 pub fn classify_script(script: &[u8]) -> ScriptType {
-    match script.len() {
-        3 => match script[0] {
-            0x00 => ScriptType::P2WPKH,
-            _ => ScriptType::P2PKH,
-        },
+    match script {
+        // OP_DUP OP_HASH160 <20 bytes> OP_EQUALVERIFY OP_CHECKSIG
+        [0x76, 0xa9, 0x14, .., 0x88, 0xac] if script.len() == 25 => ScriptType::P2PKH,
+        // OP_HASH160 <20 bytes> OP_EQUAL
+        [0xa9, 0x14, .., 0x87] if script.len() == 23 => ScriptType::P2SH,
+        // OP_0 <20 bytes>
+        [0x00, 0x14, ..] if script.len() == 22 => ScriptType::P2WPKH,
+        // OP_1 <32 bytes>
+        [0x51, 0x20, ..] if script.len() == 34 => ScriptType::P2TR,
         _ => ScriptType::Unknown,
     }
 }
 
+/// Incrementally assembles a scriptPubkey/scriptSig from opcodes and data pushes,
+/// choosing the minimal push opcode for each `push_slice` call.
+#[derive(Default)]
+pub struct Builder {
+    bytes: Vec<u8>,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Builder { bytes: Vec::new() }
+    }
+
+    pub fn push_opcode(mut self, byte: u8) -> Self {
+        self.bytes.push(byte);
+        self
+    }
+
+    // Push a data slice using the shortest applicable push opcode.
+    pub fn push_slice(mut self, data: &[u8]) -> Self {
+        match data.len() {
+            len @ 0..=0x4b => self.bytes.push(len as u8),
+            len @ 0x4c..=0xff => {
+                self.bytes.push(0x4c);
+                self.bytes.push(len as u8);
+            }
+            len @ 0x100..=0xffff => {
+                self.bytes.push(0x4d);
+                self.bytes.extend_from_slice(&(len as u16).to_le_bytes());
+            }
+            len => {
+                self.bytes.push(0x4e);
+                self.bytes.extend_from_slice(&(len as u32).to_le_bytes());
+            }
+        }
+        self.bytes.extend_from_slice(data);
+        self
+    }
+
+    // Push a small integer as OP_0 or OP_1..OP_16.
+    pub fn push_int(mut self, value: u8) -> Self {
+        self.bytes.push(match value {
+            0 => 0x00,
+            1..=16 => 0x50 + value,
+            _ => panic!("push_int only supports 0..=16"),
+        });
+        self
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// OP_DUP OP_HASH160 <pubkey_hash> OP_EQUALVERIFY OP_CHECKSIG
+pub fn new_p2pkh(pubkey_hash: &[u8; 20]) -> Vec<u8> {
+    Builder::new()
+        .push_opcode(0x76) // OP_DUP
+        .push_opcode(0xa9) // OP_HASH160
+        .push_slice(pubkey_hash)
+        .push_opcode(0x88) // OP_EQUALVERIFY
+        .push_opcode(0xac) // OP_CHECKSIG
+        .into_bytes()
+}
+
+/// OP_HASH160 <script_hash> OP_EQUAL
+pub fn new_p2sh(script_hash: &[u8; 20]) -> Vec<u8> {
+    Builder::new()
+        .push_opcode(0xa9) // OP_HASH160
+        .push_slice(script_hash)
+        .push_opcode(0x87) // OP_EQUAL
+        .into_bytes()
+}
+
+/// OP_0 <wpubkey_hash>
+pub fn new_p2wpkh(wpubkey_hash: &[u8; 20]) -> Vec<u8> {
+    Builder::new()
+        .push_int(0) // OP_0 (witness version)
+        .push_slice(wpubkey_hash)
+        .into_bytes()
+}
+
+/// OP_1 <output_key>
+pub fn new_p2tr(output_key: &[u8; 32]) -> Vec<u8> {
+    Builder::new()
+        .push_int(1) // OP_1 (witness version)
+        .push_slice(output_key)
+        .into_bytes()
+}
+
+#[cfg(test)]
+mod script_type_tests {
+    use super::*;
+
+    #[test]
+    fn classifies_each_template() {
+        assert_eq!(classify_script(&new_p2pkh(&[0x11; 20])), ScriptType::P2PKH);
+        assert_eq!(classify_script(&new_p2sh(&[0x22; 20])), ScriptType::P2SH);
+        assert_eq!(classify_script(&new_p2wpkh(&[0x33; 20])), ScriptType::P2WPKH);
+        assert_eq!(classify_script(&new_p2tr(&[0x44; 32])), ScriptType::P2TR);
+    }
+
+    #[test]
+    fn classifies_garbage_as_unknown() {
+        assert_eq!(classify_script(&[0x01, 0x02, 0x03]), ScriptType::Unknown);
+    }
+
+    #[test]
+    fn rejects_a_p2pkh_shaped_prefix_at_the_wrong_length() {
+        // Right opcodes/prefix bytes as new_p2pkh, but missing the trailing
+        // OP_EQUALVERIFY OP_CHECKSIG pair, so it must not be misclassified.
+        let mut script = new_p2pkh(&[0x11; 20]);
+        script.truncate(23);
+        assert_eq!(classify_script(&script), ScriptType::Unknown);
+    }
+}
+
 // Outpoint tuple struct
 pub struct Outpoint {
     pub txid: String,
@@ -104,6 +233,26 @@ pub enum Opcode {
     /// operations to occur without needing to otherwise manipulate or store additional copies of
     /// the data.
     OpDup,
+    /// Pushes the following `n` bytes of the script onto the stack as a single element.
+    OpPushBytes(u8),
+    /// Reads a 1-byte length prefix, then pushes that many following bytes onto the stack.
+    OpPushdata1,
+    /// Reads a 2-byte (LE) length prefix, then pushes that many following bytes onto the stack.
+    OpPushdata2,
+    /// Reads a 4-byte (LE) length prefix, then pushes that many following bytes onto the stack.
+    OpPushdata4,
+    /// Pops the top two stack items and pushes 0x01 if they are equal, 0x00 otherwise.
+    OpEqual,
+    /// Like `OpEqual`, but aborts execution if the comparison fails.
+    OpEqualVerify,
+    /// Pops the top stack item and pushes RIPEMD160(SHA256(item)).
+    OpHash160,
+    /// Begins a conditional branch; pops the top stack item to decide which branch executes.
+    OpIf,
+    /// Switches to the other branch of the innermost open `OpIf`.
+    OpElse,
+    /// Closes the innermost open `OpIf`/`OpElse` block.
+    OpEndIf,
     OpInvalid,
 }
 
@@ -113,10 +262,771 @@ impl Opcode {
         match byte {
             0xac => Ok(Opcode::OpChecksig),
             0x76 => Ok(Opcode::OpDup),
-            0x00 => Err("Invalid opcode: 0x00".to_string()),
+            // OP_0 is a zero-length push (an empty stack item), same family as
+            // the other direct push opcodes, not a parse error.
+            0x00..=0x4b => Ok(Opcode::OpPushBytes(byte)),
+            0x4c => Ok(Opcode::OpPushdata1),
+            0x4d => Ok(Opcode::OpPushdata2),
+            0x4e => Ok(Opcode::OpPushdata4),
+            0x87 => Ok(Opcode::OpEqual),
+            0x88 => Ok(Opcode::OpEqualVerify),
+            0xa9 => Ok(Opcode::OpHash160),
+            0x63 => Ok(Opcode::OpIf),
+            0x67 => Ok(Opcode::OpElse),
+            0x68 => Ok(Opcode::OpEndIf),
             _ => Ok(Opcode::OpInvalid),
         }
     }
+
+    /// Inverse of `from_byte`: the canonical byte for this opcode. `OpInvalid`
+    /// collapses many distinct unused bytes, so it maps to the sentinel 0xff,
+    /// which itself decodes back to `OpInvalid` via `from_byte`.
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            Opcode::OpChecksig => 0xac,
+            Opcode::OpDup => 0x76,
+            Opcode::OpPushBytes(n) => *n,
+            Opcode::OpPushdata1 => 0x4c,
+            Opcode::OpPushdata2 => 0x4d,
+            Opcode::OpPushdata4 => 0x4e,
+            Opcode::OpEqual => 0x87,
+            Opcode::OpEqualVerify => 0x88,
+            Opcode::OpHash160 => 0xa9,
+            Opcode::OpIf => 0x63,
+            Opcode::OpElse => 0x67,
+            Opcode::OpEndIf => 0x68,
+            Opcode::OpInvalid => 0xff,
+        }
+    }
+}
+
+impl std::fmt::Display for Opcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Opcode::OpChecksig => write!(f, "OP_CHECKSIG"),
+            Opcode::OpDup => write!(f, "OP_DUP"),
+            Opcode::OpPushBytes(n) => write!(f, "OP_PUSHBYTES_{}", n),
+            Opcode::OpPushdata1 => write!(f, "OP_PUSHDATA1"),
+            Opcode::OpPushdata2 => write!(f, "OP_PUSHDATA2"),
+            Opcode::OpPushdata4 => write!(f, "OP_PUSHDATA4"),
+            Opcode::OpEqual => write!(f, "OP_EQUAL"),
+            Opcode::OpEqualVerify => write!(f, "OP_EQUALVERIFY"),
+            Opcode::OpHash160 => write!(f, "OP_HASH160"),
+            Opcode::OpIf => write!(f, "OP_IF"),
+            Opcode::OpElse => write!(f, "OP_ELSE"),
+            Opcode::OpEndIf => write!(f, "OP_ENDIF"),
+            Opcode::OpInvalid => write!(f, "OP_INVALID"),
+        }
+    }
+}
+
+impl FromStr for Opcode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "OP_CHECKSIG" => Ok(Opcode::OpChecksig),
+            "OP_DUP" => Ok(Opcode::OpDup),
+            "OP_PUSHDATA1" => Ok(Opcode::OpPushdata1),
+            "OP_PUSHDATA2" => Ok(Opcode::OpPushdata2),
+            "OP_PUSHDATA4" => Ok(Opcode::OpPushdata4),
+            "OP_EQUAL" => Ok(Opcode::OpEqual),
+            "OP_EQUALVERIFY" => Ok(Opcode::OpEqualVerify),
+            "OP_HASH160" => Ok(Opcode::OpHash160),
+            "OP_IF" => Ok(Opcode::OpIf),
+            "OP_ELSE" => Ok(Opcode::OpElse),
+            "OP_ENDIF" => Ok(Opcode::OpEndIf),
+            "OP_INVALID" => Ok(Opcode::OpInvalid),
+            _ => s
+                .strip_prefix("OP_PUSHBYTES_")
+                .and_then(|n| n.parse::<u8>().ok())
+                .filter(|n| (0x00..=0x4b).contains(n))
+                .map(Opcode::OpPushBytes)
+                .ok_or_else(|| format!("unknown opcode name: {}", s)),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Opcode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Opcode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A single parsed element of a script: either an opcode to execute, or
+/// literal bytes that a preceding push opcode placed directly in the stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScriptItem {
+    Op(Opcode),
+    Data(Vec<u8>),
+}
+
+/// Errors that can occur while parsing or executing a script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScriptError {
+    /// A push opcode asked for more bytes than remain in the script.
+    TruncatedPush,
+    /// An `OP_IF` was never matched by a corresponding `OP_ENDIF`.
+    UnbalancedConditional,
+    /// `OP_ELSE`/`OP_ENDIF` appeared without an open `OP_IF`.
+    DanglingConditional,
+    /// An opcode needed more items on the stack than were available.
+    StackUnderflow,
+    /// `OP_EQUALVERIFY` (or another `*VERIFY` opcode) saw a falsy top element.
+    VerifyFailed,
+    /// Script finished with an empty stack or a falsy top element.
+    EvaluationFailed,
+    /// An opcode with no supported meaning (`OpInvalid`) was executed.
+    InvalidOpcode(String),
+    /// Two verifiers run under `ComparisonVerifier` disagreed on the outcome.
+    Divergence(String),
+}
+
+impl ScriptError {
+    /// The error's category, ignoring any embedded message text. Two
+    /// independent verifier implementations will essentially never produce
+    /// byte-identical error strings even when they agree on *why* a script
+    /// failed, so `ComparisonVerifier` compares on this instead of full
+    /// structural equality.
+    fn category(&self) -> std::mem::Discriminant<ScriptError> {
+        std::mem::discriminant(self)
+    }
+}
+
+/// The execution state of a single script: the main data stack and the stack
+/// of open conditional branches. The instruction sequence itself is passed
+/// into `run` rather than stored here, since a `Program` carries state across
+/// the separate scriptSig and scriptPubkey passes of `eval_script`.
+pub struct Program {
+    stack: Vec<Vec<u8>>,
+    cond_stack: Vec<bool>,
+}
+
+// Parse raw script bytes into a sequence of opcodes/pushdata items.
+fn parse_script(script: &[u8]) -> Result<Vec<ScriptItem>, ScriptError> {
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < script.len() {
+        let opcode = Opcode::from_byte(script[i]).map_err(ScriptError::InvalidOpcode)?;
+        i += 1;
+        let push_len = match opcode {
+            Opcode::OpPushBytes(n) => Some(n as usize),
+            Opcode::OpPushdata1 => {
+                let n = *script.get(i).ok_or(ScriptError::TruncatedPush)? as usize;
+                i += 1;
+                Some(n)
+            }
+            Opcode::OpPushdata2 => {
+                let bytes = script.get(i..i + 2).ok_or(ScriptError::TruncatedPush)?;
+                i += 2;
+                Some(u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+            }
+            Opcode::OpPushdata4 => {
+                let bytes = script.get(i..i + 4).ok_or(ScriptError::TruncatedPush)?;
+                i += 4;
+                Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+            }
+            _ => None,
+        };
+        match push_len {
+            Some(len) => {
+                let data = script.get(i..i + len).ok_or(ScriptError::TruncatedPush)?;
+                i += len;
+                items.push(ScriptItem::Data(data.to_vec()));
+            }
+            None => items.push(ScriptItem::Op(opcode)),
+        }
+    }
+    Ok(items)
+}
+
+impl Program {
+    fn new() -> Self {
+        Program {
+            stack: Vec::new(),
+            cond_stack: Vec::new(),
+        }
+    }
+
+    // True if every open conditional branch is currently executing (i.e. we are
+    // not inside a skipped OP_IF or OP_ELSE branch).
+    fn executing(&self) -> bool {
+        self.cond_stack.iter().all(|&b| b)
+    }
+
+    fn pop(&mut self) -> Result<Vec<u8>, ScriptError> {
+        self.stack.pop().ok_or(ScriptError::StackUnderflow)
+    }
+
+    fn push_bool(&mut self, value: bool) {
+        self.stack.push(if value { vec![0x01] } else { vec![] });
+    }
+
+    fn run(&mut self, items: &[ScriptItem], checksig: &dyn Fn(&[u8], &[u8]) -> bool) -> Result<(), ScriptError> {
+        for item in items {
+            match item {
+                ScriptItem::Data(bytes) => {
+                    if self.executing() {
+                        self.stack.push(bytes.clone());
+                    }
+                }
+                ScriptItem::Op(Opcode::OpIf) => {
+                    let branch = if self.executing() {
+                        is_truthy(&self.pop()?)
+                    } else {
+                        false
+                    };
+                    self.cond_stack.push(branch);
+                }
+                ScriptItem::Op(Opcode::OpElse) => {
+                    let top = self
+                        .cond_stack
+                        .last_mut()
+                        .ok_or(ScriptError::DanglingConditional)?;
+                    *top = !*top;
+                }
+                ScriptItem::Op(Opcode::OpEndIf) => {
+                    self.cond_stack
+                        .pop()
+                        .ok_or(ScriptError::DanglingConditional)?;
+                }
+                ScriptItem::Op(op) if !self.executing() => {
+                    // Skipped branch: only conditionals above are honored.
+                    let _ = op;
+                }
+                ScriptItem::Op(Opcode::OpDup) => {
+                    let top = self.stack.last().ok_or(ScriptError::StackUnderflow)?.clone();
+                    self.stack.push(top);
+                }
+                ScriptItem::Op(Opcode::OpEqual) => {
+                    let a = self.pop()?;
+                    let b = self.pop()?;
+                    self.push_bool(a == b);
+                }
+                ScriptItem::Op(Opcode::OpEqualVerify) => {
+                    let a = self.pop()?;
+                    let b = self.pop()?;
+                    if a != b {
+                        return Err(ScriptError::VerifyFailed);
+                    }
+                }
+                ScriptItem::Op(Opcode::OpHash160) => {
+                    let data = self.pop()?;
+                    self.stack.push(hash160(&data).to_vec());
+                }
+                ScriptItem::Op(Opcode::OpChecksig) => {
+                    let pubkey = self.pop()?;
+                    let signature = self.pop()?;
+                    self.push_bool(checksig(&signature, &pubkey));
+                }
+                ScriptItem::Op(Opcode::OpInvalid) => {
+                    return Err(ScriptError::InvalidOpcode(
+                        "encountered OP_INVALID during execution".to_string(),
+                    ));
+                }
+                ScriptItem::Op(
+                    op @ (Opcode::OpPushBytes(_)
+                    | Opcode::OpPushdata1
+                    | Opcode::OpPushdata2
+                    | Opcode::OpPushdata4),
+                ) => {
+                    // Push opcodes are consumed during parsing and never reach here;
+                    // this arm only exists to keep the match exhaustive.
+                    return Err(ScriptError::InvalidOpcode(format!("unparsed push opcode {:?}", op)));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_truthy(bytes: &[u8]) -> bool {
+    bytes.iter().any(|&b| b != 0)
+}
+
+// SHA256 followed by RIPEMD160, the hashing pair used by OP_HASH160 and
+// Base58Check addresses alike.
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    let sha = Sha256::digest(data);
+    let ripemd = Ripemd160::digest(sha);
+    ripemd.into()
+}
+
+/// Mainnet/testnet version bytes for Base58Check-encoded addresses.
+pub const P2PKH_VERSION_MAINNET: u8 = 0x00;
+pub const P2PKH_VERSION_TESTNET: u8 = 0x6f;
+pub const P2SH_VERSION_MAINNET: u8 = 0x05;
+pub const P2SH_VERSION_TESTNET: u8 = 0xc4;
+
+/// Base58Check-encode `payload` under `version`, appending the 4-byte
+/// double-SHA256 checksum before Base58-encoding.
+pub fn encode_base58check(version: u8, payload: &[u8]) -> String {
+    let mut data = Vec::with_capacity(1 + payload.len() + 4);
+    data.push(version);
+    data.extend_from_slice(payload);
+    let checksum = double_sha256(&data);
+    data.extend_from_slice(&checksum[..4]);
+    bs58::encode(data).into_string()
+}
+
+/// Decode and verify a Base58Check string, returning its version byte and payload.
+pub fn decode_base58check(addr: &str) -> Result<(u8, Vec<u8>), String> {
+    let data = bs58::decode(addr)
+        .into_vec()
+        .map_err(|e| format!("invalid base58: {}", e))?;
+    if data.len() < 5 {
+        return Err("base58check payload too short".to_string());
+    }
+    let (body, checksum) = data.split_at(data.len() - 4);
+    let expected = double_sha256(body);
+    if &expected[..4] != checksum {
+        return Err("base58check checksum mismatch".to_string());
+    }
+    Ok((body[0], body[1..].to_vec()))
+}
+
+/// Render a public key as a P2PKH address.
+pub fn p2pkh_address(pubkey: &[u8], version: u8) -> String {
+    encode_base58check(version, &hash160(pubkey))
+}
+
+/// Render a redeem script as a P2SH address.
+pub fn p2sh_address(redeem_script: &[u8], version: u8) -> String {
+    encode_base58check(version, &hash160(redeem_script))
+}
+
+#[cfg(test)]
+mod address_tests {
+    use super::*;
+
+    #[test]
+    fn base58check_round_trips() {
+        let encoded = encode_base58check(P2PKH_VERSION_MAINNET, &[0xab; 20]);
+        let (version, payload) = decode_base58check(&encoded).unwrap();
+        assert_eq!(version, P2PKH_VERSION_MAINNET);
+        assert_eq!(payload, vec![0xab; 20]);
+    }
+
+    #[test]
+    fn base58check_rejects_corrupted_checksum() {
+        let mut encoded = encode_base58check(P2SH_VERSION_MAINNET, &[0xcd; 20]);
+        encoded.push('1');
+        assert!(decode_base58check(&encoded).is_err());
+    }
+
+    #[test]
+    fn p2pkh_address_round_trips_to_its_pubkey_hash() {
+        let pubkey = [0x02; 33];
+        let addr = p2pkh_address(&pubkey, P2PKH_VERSION_MAINNET);
+        let (version, payload) = decode_base58check(&addr).unwrap();
+        assert_eq!(version, P2PKH_VERSION_MAINNET);
+        assert_eq!(payload, hash160(&pubkey));
+    }
+
+    #[test]
+    fn p2sh_address_round_trips_to_its_redeem_script_hash() {
+        let redeem_script = Builder::new().push_opcode(0x51).into_bytes();
+        let addr = p2sh_address(&redeem_script, P2SH_VERSION_TESTNET);
+        let (version, payload) = decode_base58check(&addr).unwrap();
+        assert_eq!(version, P2SH_VERSION_TESTNET);
+        assert_eq!(payload, hash160(&redeem_script));
+    }
+}
+
+/// Evaluate a scriptSig followed by a scriptPubkey against a single shared stack,
+/// the way Bitcoin's legacy script verification does. `checksig` is a pluggable
+/// signature-verification callback so this evaluator does not need to depend on
+/// a specific signing library. `flags` conditions which consensus rules apply;
+/// currently `VerifyFlags::P2SH` is honored (BIP16: when the scriptPubkey is a
+/// P2SH template, the redeem script scriptSig pushed last is also executed).
+pub fn eval_script(
+    script_sig: &[u8],
+    script_pubkey: &[u8],
+    checksig: &dyn Fn(&[u8], &[u8]) -> bool,
+    flags: VerifyFlags,
+) -> Result<bool, ScriptError> {
+    let sig_items = parse_script(script_sig)?;
+    let pubkey_items = parse_script(script_pubkey)?;
+
+    let mut program = Program::new();
+    program.run(&sig_items, checksig)?;
+    if !program.cond_stack.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
+    }
+    program.run(&pubkey_items, checksig)?;
+
+    if !program.cond_stack.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
+    }
+
+    match program.stack.last() {
+        Some(top) if is_truthy(top) => {}
+        _ => return Err(ScriptError::EvaluationFailed),
+    }
+
+    if flags.contains(VerifyFlags::P2SH) && classify_script(script_pubkey) == ScriptType::P2SH {
+        return eval_p2sh_redeem_script(&sig_items, checksig);
+    }
+
+    Ok(true)
+}
+
+// BIP16: re-run the scriptSig's pushes (minus the final one, which is the
+// serialized redeem script) followed by the redeem script itself, and use
+// that second evaluation's result instead of the plain scriptPubkey check.
+fn eval_p2sh_redeem_script(
+    sig_items: &[ScriptItem],
+    checksig: &dyn Fn(&[u8], &[u8]) -> bool,
+) -> Result<bool, ScriptError> {
+    let (redeem_script, rest) = match sig_items.split_last() {
+        Some((ScriptItem::Data(redeem_script), rest)) => (redeem_script, rest),
+        _ => return Err(ScriptError::EvaluationFailed),
+    };
+
+    let mut program = Program::new();
+    program.run(rest, checksig)?;
+    if !program.cond_stack.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
+    }
+    program.run(&parse_script(redeem_script)?, checksig)?;
+
+    if !program.cond_stack.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
+    }
+
+    match program.stack.last() {
+        Some(top) if is_truthy(top) => Ok(true),
+        _ => Err(ScriptError::EvaluationFailed),
+    }
+}
+
+#[cfg(test)]
+mod conditional_tests {
+    use super::*;
+
+    const OP_IF: u8 = 0x63;
+    const OP_ELSE: u8 = 0x67;
+    const OP_ENDIF: u8 = 0x68;
+
+    fn accept_all(_sig: &[u8], _pubkey: &[u8]) -> bool {
+        true
+    }
+
+    #[test]
+    fn if_true_branch_executes_and_else_branch_is_skipped() {
+        let script_sig = Builder::new().push_slice(&[1]).into_bytes();
+        let script_pubkey = Builder::new()
+            .push_opcode(OP_IF)
+            .push_slice(&[1])
+            .push_opcode(OP_ELSE)
+            .push_slice(&[])
+            .push_opcode(OP_ENDIF)
+            .into_bytes();
+
+        assert_eq!(
+            eval_script(&script_sig, &script_pubkey, &accept_all, VerifyFlags::default()),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn if_false_branch_is_skipped_and_else_branch_executes() {
+        let script_sig = Builder::new().push_slice(&[]).into_bytes();
+        let script_pubkey = Builder::new()
+            .push_opcode(OP_IF)
+            .push_slice(&[1])
+            .push_opcode(OP_ELSE)
+            .push_slice(&[1])
+            .push_opcode(OP_ENDIF)
+            .into_bytes();
+
+        assert_eq!(
+            eval_script(&script_sig, &script_pubkey, &accept_all, VerifyFlags::default()),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn nested_conditionals_pick_the_true_leaf() {
+        let script_pubkey = Builder::new()
+            .push_slice(&[1]) // outer condition: true
+            .push_opcode(OP_IF)
+            .push_slice(&[]) // inner condition: false
+            .push_opcode(OP_IF)
+            .push_slice(&[9]) // inner-true branch: skipped
+            .push_opcode(OP_ELSE)
+            .push_slice(&[7]) // inner-false branch: executes
+            .push_opcode(OP_ENDIF)
+            .push_opcode(OP_ENDIF)
+            .into_bytes();
+
+        assert_eq!(eval_script(&[], &script_pubkey, &accept_all, VerifyFlags::default()), Ok(true));
+    }
+
+    #[test]
+    fn a_skipped_outer_branch_never_pops_for_its_nested_conditional() {
+        // The outer branch is false, so the inner OP_IF's condition byte is
+        // never pushed. If execution still tried to pop it, this would fail
+        // with StackUnderflow instead of taking the outer OP_ELSE.
+        let script_pubkey = Builder::new()
+            .push_slice(&[]) // outer condition: false
+            .push_opcode(OP_IF)
+            .push_slice(&[1])
+            .push_opcode(OP_IF)
+            .push_slice(&[2])
+            .push_opcode(OP_ENDIF)
+            .push_opcode(OP_ELSE)
+            .push_slice(&[5])
+            .push_opcode(OP_ENDIF)
+            .into_bytes();
+
+        assert_eq!(eval_script(&[], &script_pubkey, &accept_all, VerifyFlags::default()), Ok(true));
+    }
+
+    #[test]
+    fn an_unclosed_if_is_rejected_as_unbalanced() {
+        let script_sig = Builder::new().push_slice(&[1]).into_bytes();
+        let script_pubkey = Builder::new().push_opcode(OP_IF).push_slice(&[1]).into_bytes();
+
+        assert_eq!(
+            eval_script(&script_sig, &script_pubkey, &accept_all, VerifyFlags::default()),
+            Err(ScriptError::UnbalancedConditional)
+        );
+    }
+
+    #[test]
+    fn a_scriptsig_with_its_own_dangling_if_is_rejected_even_if_the_combined_stream_balances() {
+        // The scriptSig's OP_IF is unclosed on its own, but the scriptPubkey
+        // happens to contain a matching OP_ENDIF. Each script must be checked
+        // for balance independently, the way Bitcoin evaluates them as two
+        // separate EvalScript calls, so this must still fail.
+        let script_sig = Builder::new().push_slice(&[1]).push_opcode(OP_IF).into_bytes();
+        let script_pubkey = Builder::new().push_opcode(OP_ENDIF).into_bytes();
+
+        assert_eq!(
+            eval_script(&script_sig, &script_pubkey, &accept_all, VerifyFlags::default()),
+            Err(ScriptError::UnbalancedConditional)
+        );
+    }
+
+    #[test]
+    fn a_dangling_else_with_no_open_if_is_rejected() {
+        let script_pubkey = Builder::new().push_opcode(OP_ELSE).into_bytes();
+
+        assert_eq!(
+            eval_script(&[], &script_pubkey, &accept_all, VerifyFlags::default()),
+            Err(ScriptError::DanglingConditional)
+        );
+    }
+
+    #[test]
+    fn a_dangling_endif_with_no_open_if_is_rejected() {
+        let script_pubkey = Builder::new().push_opcode(OP_ENDIF).into_bytes();
+
+        assert_eq!(
+            eval_script(&[], &script_pubkey, &accept_all, VerifyFlags::default()),
+            Err(ScriptError::DanglingConditional)
+        );
+    }
+}
+
+/// Consensus rule toggles threaded through script verification, mirroring the
+/// flags Bitcoin Core passes to its own script interpreter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VerifyFlags(pub u32);
+
+impl VerifyFlags {
+    pub const NONE: u32 = 0;
+    pub const P2SH: u32 = 1 << 0;
+    pub const WITNESS: u32 = 1 << 1;
+    pub const NULLDUMMY: u32 = 1 << 2;
+
+    pub fn contains(self, flag: u32) -> bool {
+        self.0 & flag == flag
+    }
+}
+
+impl std::ops::BitOr for VerifyFlags {
+    type Output = VerifyFlags;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        VerifyFlags(self.0 | rhs.0)
+    }
+}
+
+/// A pluggable script verification backend, so the crate's native evaluator can
+/// be run side by side with a reference or optimized implementation.
+pub trait Verifier {
+    fn verify(
+        &self,
+        script_sig: &[u8],
+        script_pubkey: &[u8],
+        flags: VerifyFlags,
+    ) -> Result<(), ScriptError>;
+}
+
+/// Runs scripts through the crate's own `eval_script`, forwarding `flags` so
+/// two `NativeVerifier`s compared under different flag values actually apply
+/// different consensus rules rather than silently agreeing.
+pub struct NativeVerifier<'a> {
+    pub checksig: &'a dyn Fn(&[u8], &[u8]) -> bool,
+}
+
+impl Verifier for NativeVerifier<'_> {
+    fn verify(
+        &self,
+        script_sig: &[u8],
+        script_pubkey: &[u8],
+        flags: VerifyFlags,
+    ) -> Result<(), ScriptError> {
+        eval_script(script_sig, script_pubkey, self.checksig, flags).map(|_| ())
+    }
+}
+
+/// Runs two verifiers over the same inputs and fails loudly if their
+/// accept/reject results (or error categories) diverge, catching consensus
+/// discrepancies between the crate's evaluator and a reference implementation.
+pub struct ComparisonVerifier<A, B> {
+    pub primary: A,
+    pub reference: B,
+}
+
+impl<A: Verifier, B: Verifier> Verifier for ComparisonVerifier<A, B> {
+    fn verify(
+        &self,
+        script_sig: &[u8],
+        script_pubkey: &[u8],
+        flags: VerifyFlags,
+    ) -> Result<(), ScriptError> {
+        let primary = self.primary.verify(script_sig, script_pubkey, flags);
+        let reference = self.reference.verify(script_sig, script_pubkey, flags);
+        match (&primary, &reference) {
+            (Ok(()), Ok(())) => Ok(()),
+            (Err(a), Err(b)) if a.category() == b.category() => Err(a.clone()),
+            _ => Err(ScriptError::Divergence(format!(
+                "verifiers disagreed: primary={:?}, reference={:?}",
+                primary, reference
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod verifier_tests {
+    use super::*;
+
+    fn accept_all(_sig: &[u8], _pubkey: &[u8]) -> bool {
+        true
+    }
+
+    fn reject_all(_sig: &[u8], _pubkey: &[u8]) -> bool {
+        false
+    }
+
+    #[test]
+    fn comparison_verifier_agrees_when_both_sides_match() {
+        let pubkey = [0u8; 33];
+        let script_sig = Builder::new().push_slice(&[0u8; 65]).push_slice(&pubkey).into_bytes();
+        let script_pubkey = new_p2pkh(&hash160(&pubkey));
+
+        let verifier = ComparisonVerifier {
+            primary: NativeVerifier { checksig: &accept_all },
+            reference: NativeVerifier { checksig: &accept_all },
+        };
+        assert!(verifier
+            .verify(&script_sig, &script_pubkey, VerifyFlags::default())
+            .is_ok());
+    }
+
+    #[test]
+    fn comparison_verifier_flags_divergence() {
+        let pubkey = [0u8; 33];
+        let script_sig = Builder::new().push_slice(&[0u8; 65]).push_slice(&pubkey).into_bytes();
+        let script_pubkey = new_p2pkh(&hash160(&pubkey));
+
+        let verifier = ComparisonVerifier {
+            primary: NativeVerifier { checksig: &accept_all },
+            reference: NativeVerifier { checksig: &reject_all },
+        };
+        let result = verifier.verify(&script_sig, &script_pubkey, VerifyFlags::default());
+        assert!(matches!(result, Err(ScriptError::Divergence(_))));
+    }
+
+    struct FixedResult(Result<(), ScriptError>);
+
+    impl Verifier for FixedResult {
+        fn verify(&self, _: &[u8], _: &[u8], _: VerifyFlags) -> Result<(), ScriptError> {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn comparison_verifier_agrees_on_same_error_category_despite_different_messages() {
+        // Two independent implementations of the same failure mode will rarely
+        // produce byte-identical error text; the same *category* should still
+        // count as agreement, not a spurious Divergence.
+        let verifier = ComparisonVerifier {
+            primary: FixedResult(Err(ScriptError::InvalidOpcode("native: bad opcode".to_string()))),
+            reference: FixedResult(Err(ScriptError::InvalidOpcode(
+                "reference-engine: unrecognized opcode byte".to_string(),
+            ))),
+        };
+        let result = verifier.verify(&[], &[], VerifyFlags::default());
+        assert!(matches!(result, Err(ScriptError::InvalidOpcode(_))));
+    }
+
+    #[test]
+    fn p2sh_flag_changes_the_outcome_of_an_otherwise_passing_script() {
+        // The redeem script just demands its top two stack items be equal.
+        const OP_EQUAL: u8 = 0x87;
+        let redeem_script = Builder::new().push_opcode(OP_EQUAL).into_bytes();
+        let script_pubkey = new_p2sh(&hash160(&redeem_script));
+
+        // scriptSig pushes two *different* values, so the hash-equality check
+        // in the plain scriptPubkey still passes (the redeem script's hash is
+        // correct), but actually running the redeem script must fail.
+        let script_sig = Builder::new()
+            .push_slice(&[1])
+            .push_slice(&[2])
+            .push_slice(&redeem_script)
+            .into_bytes();
+
+        assert_eq!(
+            eval_script(&script_sig, &script_pubkey, &accept_all, VerifyFlags::default()),
+            Ok(true)
+        );
+        assert_eq!(
+            eval_script(
+                &script_sig,
+                &script_pubkey,
+                &accept_all,
+                VerifyFlags(VerifyFlags::P2SH)
+            ),
+            Err(ScriptError::EvaluationFailed)
+        );
+
+        let verifier = ComparisonVerifier {
+            primary: NativeVerifier { checksig: &accept_all },
+            reference: NativeVerifier { checksig: &accept_all },
+        };
+        let result = verifier.verify(&script_sig, &script_pubkey, VerifyFlags(VerifyFlags::P2SH));
+        assert!(matches!(result, Err(ScriptError::EvaluationFailed)));
+    }
 }
 
 // TODO: Add necessary derive traits
@@ -127,8 +1037,496 @@ pub struct UTXO {
     pub value: u64,
 }
 
+// Renders `txid` as a hex string instead of a raw byte array, matching how
+// this crate's own hashes already round-trip through `bytes_to_hex`/`hex_to_bytes`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for UTXO {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("UTXO", 3)?;
+        state.serialize_field("txid", &bytes_to_hex(&self.txid))?;
+        state.serialize_field("vout", &self.vout)?;
+        state.serialize_field("value", &self.value)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for UTXO {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        struct UtxoFields {
+            txid: String,
+            vout: u32,
+            value: u64,
+        }
+        let fields = UtxoFields::deserialize(deserializer)?;
+        let txid = hex_to_bytes(&fields.txid).map_err(serde::de::Error::custom)?;
+        Ok(UTXO {
+            txid,
+            vout: fields.vout,
+            value: fields.value,
+        })
+    }
+}
+
 pub fn consume_utxo(mut utxo: UTXO) -> UTXO {
     // Mark the UTXO as spent by setting its value to 0
     utxo.value = 0;
     utxo
 }
+
+// Encode a length as Bitcoin's CompactSize ("VarInt"): values below 0xFD are a
+// single byte, larger values are prefixed with 0xFD/0xFE/0xFF and a fixed-width
+// little-endian integer.
+fn write_varint(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0xfc => out.push(value as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        0x10000..=0xffffffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+// Decode a CompactSize from the front of `bytes`, returning the value and the
+// number of bytes consumed.
+fn read_varint(bytes: &[u8]) -> Result<(u64, usize), String> {
+    match bytes.first() {
+        None => Err("VarInt: empty input".to_string()),
+        Some(0xfd) => {
+            let b = bytes.get(1..3).ok_or("VarInt: truncated 0xfd prefix")?;
+            Ok((u16::from_le_bytes([b[0], b[1]]) as u64, 3))
+        }
+        Some(0xfe) => {
+            let b = bytes.get(1..5).ok_or("VarInt: truncated 0xfe prefix")?;
+            Ok((u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as u64, 5))
+        }
+        Some(0xff) => {
+            let b = bytes.get(1..9).ok_or("VarInt: truncated 0xff prefix")?;
+            Ok((
+                u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+                9,
+            ))
+        }
+        Some(&first) => Ok((first as u64, 1)),
+    }
+}
+
+/// One transaction input: the outpoint it spends, its unlocking script, and sequence number.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxIn {
+    pub txid: [u8; 32],
+    pub vout: u32,
+    pub script_sig: Vec<u8>,
+    pub sequence: u32,
+}
+
+/// One transaction output: the amount in satoshis and the locking script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxOut {
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+/// A full (non-segwit-serialized) Bitcoin transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transaction {
+    pub version: u32,
+    pub inputs: Vec<TxIn>,
+    pub outputs: Vec<TxOut>,
+    pub locktime: u32,
+}
+
+impl Transaction {
+    /// Serialize to the raw consensus wire format.
+    pub fn consensus_encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.version.to_le_bytes());
+
+        write_varint(&mut out, self.inputs.len() as u64);
+        for input in &self.inputs {
+            out.extend_from_slice(&input.txid);
+            out.extend_from_slice(&input.vout.to_le_bytes());
+            write_varint(&mut out, input.script_sig.len() as u64);
+            out.extend_from_slice(&input.script_sig);
+            out.extend_from_slice(&input.sequence.to_le_bytes());
+        }
+
+        write_varint(&mut out, self.outputs.len() as u64);
+        for output in &self.outputs {
+            out.extend_from_slice(&output.value.to_le_bytes());
+            write_varint(&mut out, output.script_pubkey.len() as u64);
+            out.extend_from_slice(&output.script_pubkey);
+        }
+
+        out.extend_from_slice(&self.locktime.to_le_bytes());
+        out
+    }
+
+    /// Parse a transaction from the raw consensus wire format.
+    pub fn consensus_decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut pos = 0;
+        let take = |pos: &mut usize, len: usize| -> Result<&[u8], String> {
+            let end = pos
+                .checked_add(len)
+                .ok_or_else(|| "unexpected end of transaction bytes".to_string())?;
+            let slice = bytes
+                .get(*pos..end)
+                .ok_or_else(|| "unexpected end of transaction bytes".to_string())?;
+            *pos = end;
+            Ok(slice)
+        };
+
+        let version = u32::from_le_bytes(take(&mut pos, 4)?.try_into().unwrap());
+
+        let (input_count, used) = read_varint(&bytes[pos..])?;
+        pos += used;
+        // Each input needs at least 41 bytes (32-byte txid + 4-byte vout + a
+        // 1-byte minimum scriptSig length + 4-byte sequence); clamp the
+        // preallocation against what's actually left so a corrupted or
+        // malicious VarInt can't trigger a capacity-overflow abort.
+        let input_cap = (input_count as usize).min(bytes.len().saturating_sub(pos) / 41);
+        let mut inputs = Vec::with_capacity(input_cap);
+        for _ in 0..input_count {
+            let txid = take(&mut pos, 32)?.try_into().unwrap();
+            let vout = u32::from_le_bytes(take(&mut pos, 4)?.try_into().unwrap());
+            let (script_len, used) = read_varint(&bytes[pos..])?;
+            pos += used;
+            let script_sig = take(&mut pos, script_len as usize)?.to_vec();
+            let sequence = u32::from_le_bytes(take(&mut pos, 4)?.try_into().unwrap());
+            inputs.push(TxIn {
+                txid,
+                vout,
+                script_sig,
+                sequence,
+            });
+        }
+
+        let (output_count, used) = read_varint(&bytes[pos..])?;
+        pos += used;
+        // Each output needs at least 9 bytes (8-byte value + a 1-byte minimum
+        // scriptPubkey length); same clamping rationale as `input_cap` above.
+        let output_cap = (output_count as usize).min(bytes.len().saturating_sub(pos) / 9);
+        let mut outputs = Vec::with_capacity(output_cap);
+        for _ in 0..output_count {
+            let value = u64::from_le_bytes(take(&mut pos, 8)?.try_into().unwrap());
+            let (script_len, used) = read_varint(&bytes[pos..])?;
+            pos += used;
+            let script_pubkey = take(&mut pos, script_len as usize)?.to_vec();
+            outputs.push(TxOut {
+                value,
+                script_pubkey,
+            });
+        }
+
+        let locktime = u32::from_le_bytes(take(&mut pos, 4)?.try_into().unwrap());
+
+        Ok(Transaction {
+            version,
+            inputs,
+            outputs,
+            locktime,
+        })
+    }
+}
+
+/// BIP143 sighash types, as carried in the low byte of the 4-byte `sighash_type` field.
+pub const SIGHASH_ALL: u32 = 0x01;
+pub const SIGHASH_NONE: u32 = 0x02;
+pub const SIGHASH_SINGLE: u32 = 0x03;
+pub const SIGHASH_ANYONECANPAY: u32 = 0x80;
+
+fn double_sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(Sha256::digest(data)).into()
+}
+
+/// Compute the BIP143 segwit v0 sighash for signing a P2WPKH input.
+///
+/// `script_code` is the P2PKH-style script being signed over (typically
+/// `new_p2pkh(pubkey_hash)` for a P2WPKH input), `value` is the amount in
+/// satoshis committed by the prevout being spent, and `sighash_type` is the
+/// 4-byte (but really one meaningful byte) sighash flag combination.
+pub fn sighash_p2wpkh(
+    tx: &Transaction,
+    input_index: usize,
+    script_code: &[u8],
+    value: u64,
+    sighash_type: u32,
+) -> [u8; 32] {
+    let anyonecanpay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+    let base_type = sighash_type & 0x1f;
+
+    let hash_prevouts = if anyonecanpay {
+        [0u8; 32]
+    } else {
+        let mut buf = Vec::with_capacity(tx.inputs.len() * 36);
+        for input in &tx.inputs {
+            buf.extend_from_slice(&input.txid);
+            buf.extend_from_slice(&input.vout.to_le_bytes());
+        }
+        double_sha256(&buf)
+    };
+
+    let hash_sequence = if !anyonecanpay && base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE {
+        let mut buf = Vec::with_capacity(tx.inputs.len() * 4);
+        for input in &tx.inputs {
+            buf.extend_from_slice(&input.sequence.to_le_bytes());
+        }
+        double_sha256(&buf)
+    } else {
+        [0u8; 32]
+    };
+
+    let hash_outputs = if base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE {
+        let mut buf = Vec::new();
+        for output in &tx.outputs {
+            buf.extend_from_slice(&output.value.to_le_bytes());
+            write_varint(&mut buf, output.script_pubkey.len() as u64);
+            buf.extend_from_slice(&output.script_pubkey);
+        }
+        double_sha256(&buf)
+    } else if base_type == SIGHASH_SINGLE && input_index < tx.outputs.len() {
+        let output = &tx.outputs[input_index];
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&output.value.to_le_bytes());
+        write_varint(&mut buf, output.script_pubkey.len() as u64);
+        buf.extend_from_slice(&output.script_pubkey);
+        double_sha256(&buf)
+    } else {
+        [0u8; 32]
+    };
+
+    let input = &tx.inputs[input_index];
+    let mut preimage = Vec::new();
+    preimage.extend_from_slice(&tx.version.to_le_bytes());
+    preimage.extend_from_slice(&hash_prevouts);
+    preimage.extend_from_slice(&hash_sequence);
+    preimage.extend_from_slice(&input.txid);
+    preimage.extend_from_slice(&input.vout.to_le_bytes());
+    write_varint(&mut preimage, script_code.len() as u64);
+    preimage.extend_from_slice(script_code);
+    preimage.extend_from_slice(&value.to_le_bytes());
+    preimage.extend_from_slice(&input.sequence.to_le_bytes());
+    preimage.extend_from_slice(&hash_outputs);
+    preimage.extend_from_slice(&tx.locktime.to_le_bytes());
+    preimage.extend_from_slice(&sighash_type.to_le_bytes());
+
+    double_sha256(&preimage)
+}
+
+#[cfg(test)]
+mod transaction_tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_a_simple_transaction() {
+        let tx = Transaction {
+            version: 2,
+            inputs: vec![TxIn {
+                txid: [0x11; 32],
+                vout: 1,
+                script_sig: vec![0x01, 0x02, 0x03],
+                sequence: 0xffffffff,
+            }],
+            outputs: vec![TxOut {
+                value: 5_000_000_000,
+                script_pubkey: new_p2pkh(&[0x22; 20]),
+            }],
+            locktime: 0,
+        };
+
+        let encoded = tx.consensus_encode();
+        let decoded = Transaction::consensus_decode(&encoded).unwrap();
+        assert_eq!(tx, decoded);
+    }
+
+    #[test]
+    fn consensus_decode_rejects_a_huge_count_instead_of_aborting() {
+        let mut bytes = 2u32.to_le_bytes().to_vec(); // version
+        bytes.push(0xff); // VarInt: 8-byte form
+        bytes.extend_from_slice(&(u64::MAX / 2).to_le_bytes()); // bogus input count
+        assert!(Transaction::consensus_decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn consensus_decode_rejects_a_huge_per_input_script_length_instead_of_overflowing() {
+        let mut bytes = 2u32.to_le_bytes().to_vec(); // version
+        bytes.push(0x01); // input count: 1
+        bytes.extend_from_slice(&[0x11; 32]); // txid
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // vout
+        bytes.push(0xff); // VarInt: 8-byte form
+        bytes.extend_from_slice(&(u64::MAX - 10).to_le_bytes()); // bogus scriptSig length
+        assert!(Transaction::consensus_decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn sighash_p2wpkh_changes_with_anyonecanpay() {
+        let tx = Transaction {
+            version: 2,
+            inputs: vec![TxIn {
+                txid: [0x11; 32],
+                vout: 0,
+                script_sig: vec![],
+                sequence: 0xffffffff,
+            }],
+            outputs: vec![TxOut {
+                value: 4_000_000_000,
+                script_pubkey: new_p2pkh(&[0x22; 20]),
+            }],
+            locktime: 0,
+        };
+        let script_code = new_p2pkh(&[0x33; 20]);
+
+        let all = sighash_p2wpkh(&tx, 0, &script_code, 5_000_000_000, SIGHASH_ALL);
+        let anyonecanpay = sighash_p2wpkh(
+            &tx,
+            0,
+            &script_code,
+            5_000_000_000,
+            SIGHASH_ALL | SIGHASH_ANYONECANPAY,
+        );
+        assert_ne!(all, anyonecanpay);
+    }
+
+    #[test]
+    fn sighash_p2wpkh_matches_the_bip143_native_p2wpkh_test_vector() {
+        // The BIP143 "Native P2WPKH" worked example: a 2-input, 2-output
+        // transaction signing its second input (the segwit one).
+        let tx = Transaction {
+            version: 1,
+            inputs: vec![
+                TxIn {
+                    txid: decode_hex(
+                        "fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f",
+                    )
+                    .unwrap()
+                    .try_into()
+                    .unwrap(),
+                    vout: 0,
+                    script_sig: vec![],
+                    sequence: 0xffffffee,
+                },
+                TxIn {
+                    txid: decode_hex(
+                        "ef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a",
+                    )
+                    .unwrap()
+                    .try_into()
+                    .unwrap(),
+                    vout: 1,
+                    script_sig: vec![],
+                    sequence: 0xffffffff,
+                },
+            ],
+            outputs: vec![
+                TxOut {
+                    value: 112_340_000,
+                    script_pubkey: decode_hex(
+                        "76a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac",
+                    )
+                    .unwrap(),
+                },
+                TxOut {
+                    value: 223_450_000,
+                    script_pubkey: decode_hex(
+                        "76a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac",
+                    )
+                    .unwrap(),
+                },
+            ],
+            locktime: 0x11,
+        };
+        let script_code = decode_hex("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac").unwrap();
+
+        let sighash = sighash_p2wpkh(&tx, 1, &script_code, 600_000_000, SIGHASH_ALL);
+
+        assert_eq!(
+            bytes_to_hex(&sighash),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+    }
+
+    #[test]
+    fn varint_round_trips_across_all_size_classes() {
+        for value in [0u64, 0xfc, 0xfd, 0xffff, 0x10000, 0xffffffff, 0x1_0000_0000] {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+            let (decoded, used) = read_varint(&out).unwrap();
+            assert_eq!(decoded, value);
+            assert_eq!(used, out.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod opcode_name_tests {
+    use super::*;
+
+    #[test]
+    fn opcode_round_trips_through_its_name_and_byte() {
+        let opcodes = [
+            Opcode::OpChecksig,
+            Opcode::OpDup,
+            Opcode::OpPushBytes(20),
+            Opcode::OpPushdata1,
+            Opcode::OpEqual,
+            Opcode::OpEqualVerify,
+            Opcode::OpHash160,
+            Opcode::OpIf,
+            Opcode::OpElse,
+            Opcode::OpEndIf,
+        ];
+        for op in opcodes {
+            let name = op.to_string();
+            assert_eq!(name.parse::<Opcode>().unwrap(), op);
+            assert_eq!(Opcode::from_byte(op.to_byte()).unwrap(), op);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests {
+    use super::*;
+
+    #[test]
+    fn opcode_serializes_as_its_name() {
+        let json = serde_json::to_string(&Opcode::OpHash160).unwrap();
+        assert_eq!(json, "\"OP_HASH160\"");
+        let back: Opcode = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, Opcode::OpHash160);
+    }
+
+    #[test]
+    fn script_type_serializes_as_a_tagged_string() {
+        let json = serde_json::to_string(&ScriptType::P2WPKH).unwrap();
+        assert_eq!(json, "\"P2WPKH\"");
+    }
+
+    #[test]
+    fn utxo_serializes_txid_as_hex() {
+        let utxo = UTXO {
+            txid: vec![0xde, 0xad, 0xbe, 0xef],
+            vout: 0,
+            value: 1_000,
+        };
+        let json = serde_json::to_string(&utxo).unwrap();
+        assert!(json.contains("\"deadbeef\""));
+        let back: UTXO = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, utxo);
+    }
+}